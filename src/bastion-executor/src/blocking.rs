@@ -1,62 +1,241 @@
 //!
 //! Pool of threads to run heavy processes
 //!
-//! We spawn futures onto the pool with [spawn_blocking] method of global run queue or
-//! with corresponding [Worker]'s spawn method.
+//! A [`BlockingPool`] owns its own injector queue, its own worker-local steal
+//! queues and its own [`DynamicPoolManager`]; [`crate::executor::Executor`]
+//! composes one together with a [`crate::pool::AsyncPool`] to form an
+//! independently-tunable executor. Most callers don't need this type
+//! directly — see [`crate::executor::spawn_blocking`] for the process-wide
+//! default pool.
+//!
+//! Unlike the async pool, this pool is bounded and self-trimming: the
+//! [`DynamicPoolManager`] won't scale it past [`PoolBuilder::max_threads`],
+//! and dynamic worker threads above the low watermark that sit idle past
+//! [`PoolBuilder::keep_alive`] retire on their own, shrinking the pool back
+//! toward the watermark.
 
+use crate::builder::PoolBuilder;
+use crate::metrics::{SchedulerMetrics, WorkerMetrics};
 use crate::thread_manager::{DynamicPoolManager, DynamicRunner};
-use crossbeam_channel::{unbounded, Receiver, Sender};
-use lazy_static::lazy_static;
+use crate::worker::{self, WorkerEntry};
+use crossbeam_deque::{Injector, Stealer, Worker as Deque};
 use lightproc::lightproc::LightProc;
 use lightproc::proc_stack::ProcStack;
 use lightproc::recoverable_handle::RecoverableHandle;
-use once_cell::sync::{Lazy, OnceCell};
+use once_cell::sync::OnceCell;
 use std::future::Future;
-use std::iter::Iterator;
-use std::sync::Arc;
-use std::time::Duration;
-use std::{env, thread};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
 #[cfg(feature = "runtime-tokio")]
 use tokio::runtime;
 use tracing::trace;
 
-/// If low watermark isn't configured this is the default scaler value.
-/// This value is used for the heuristics of the scaler
-const DEFAULT_LOW_WATERMARK: u64 = 2;
-
-const THREAD_RECV_TIMEOUT: Duration = Duration::from_millis(100);
-
-/// Spawns a blocking task.
+/// Spawns a blocking task onto the process-wide default executor.
 ///
-/// The task will be spawned onto a thread pool specifically dedicated to blocking tasks.
+/// Kept for backwards compatibility; prefer [`crate::executor::spawn_blocking`].
 pub fn spawn_blocking<F, R>(future: F, stack: ProcStack) -> RecoverableHandle<R>
 where
     F: Future<Output = R> + Send + 'static,
     R: Send + 'static,
 {
-    let (task, handle) = LightProc::recoverable(future, schedule, stack);
-    task.schedule();
-    handle
+    crate::executor::spawn_blocking(future, stack)
+}
+
+/// An isolated blocking-task thread pool: its own injector queue, its own
+/// worker-local steal queues, and its own [`DynamicPoolManager`].
+pub(crate) struct BlockingPool {
+    injector: Injector<LightProc>,
+    workers: RwLock<Vec<WorkerEntry>>,
+    tasks_scheduled: AtomicU64,
+    /// Worker threads this pool has committed to having alive: the initial
+    /// low-watermark batch plus every scale-up signalled to the manager since,
+    /// minus however many have retired. Checked and bumped atomically at
+    /// scale-up decision time in [`Self::schedule`], so a burst of concurrent
+    /// callers can't all observe room under `max_threads` off the (lagging)
+    /// `workers` registry and overshoot it before any of the new threads
+    /// finish [`Self::register_worker`].
+    reserved_threads: AtomicU64,
+    manager: OnceCell<Arc<DynamicPoolManager>>,
+    config: PoolBuilder,
+}
+
+impl BlockingPool {
+    /// Creates a new pool with the default configuration, spinning up its
+    /// own isolated set of worker threads.
+    pub(crate) fn new() -> Arc<BlockingPool> {
+        Self::build(PoolBuilder::new())
+    }
+
+    /// Creates a new pool configured by `config`, spinning up its own
+    /// isolated set of worker threads.
+    pub(crate) fn build(config: PoolBuilder) -> Arc<BlockingPool> {
+        let pool = Arc::new(BlockingPool {
+            injector: Injector::new(),
+            workers: RwLock::new(Vec::new()),
+            tasks_scheduled: AtomicU64::new(0),
+            // `manager.initialize()` below spins up `effective_low_watermark`
+            // threads unconditionally, outside of `schedule`'s reservation.
+            reserved_threads: AtomicU64::new(config.effective_low_watermark()),
+            manager: OnceCell::new(),
+            config,
+        });
+
+        let runner = Arc::new(BlockingRunner {
+            pool: pool.clone(),
+        });
+        let manager = Arc::new(DynamicPoolManager::new(pool.config.clone(), runner));
+        manager.initialize();
+        pool.manager
+            .set(manager)
+            .expect("pool manager already initialized");
+
+        pool
+    }
+
+    /// Spawns a blocking task onto this pool.
+    pub(crate) fn spawn_blocking<F, R>(
+        self: &Arc<Self>,
+        future: F,
+        stack: ProcStack,
+    ) -> RecoverableHandle<R>
+    where
+        F: Future<Output = R> + Send + 'static,
+        R: Send + 'static,
+    {
+        let pool = self.clone();
+        let (task, handle) = LightProc::recoverable(future, move |t| pool.schedule(t), stack);
+        task.schedule();
+        handle
+    }
+
+    /// Enqueues work, pushing onto the calling thread's local queue if it is
+    /// one of this pool's own workers, or the shared injector otherwise, and
+    /// spinning up needed amount of threads based on the previous
+    /// statistics. The [`DynamicPoolManager`] won't scale past
+    /// [`PoolBuilder::max_threads`], if one is configured.
+    fn schedule(&self, t: LightProc) {
+        worker::push_local_or(t, &self.injector);
+        self.tasks_scheduled.fetch_add(1, Ordering::Relaxed);
+
+        // Reserve a slot before signalling the manager, unless we're already
+        // at the configured ceiling, so a burst of concurrent `schedule`
+        // calls can't all slip past the cap before any of them register.
+        if self.try_reserve_thread() {
+            self.manager
+                .get()
+                .expect("pool manager not initialized")
+                .increment_frequency();
+        }
+    }
+
+    /// Atomically claims one more thread against [`PoolBuilder::max_threads`],
+    /// if one is configured, returning whether the claim succeeded.
+    fn try_reserve_thread(&self) -> bool {
+        let Some(max) = self.config.effective_max_threads() else {
+            return true;
+        };
+        self.reserved_threads
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |reserved| {
+                (reserved < max).then_some(reserved + 1)
+            })
+            .is_ok()
+    }
+
+    /// Releases a thread's claim on [`Self::reserved_threads`], once it
+    /// retires, so a later `schedule` can reclaim the slot.
+    fn release_thread(&self) {
+        let _ = self
+            .reserved_threads
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |reserved| {
+                reserved.checked_sub(1)
+            });
+    }
+
+    /// Creates a fresh local run queue and metrics counters for a newly
+    /// spawned worker thread, registering its [Stealer] with the pool so
+    /// other workers can steal from it.
+    fn register_worker(&self) -> (Deque<LightProc>, Arc<WorkerMetrics>) {
+        let local = Deque::new_lifo();
+        let metrics = Arc::new(WorkerMetrics::default());
+        self.workers
+            .write()
+            .expect("blocking: workers lock poisoned")
+            .push(WorkerEntry {
+                stealer: local.stealer(),
+                metrics: metrics.clone(),
+            });
+        (local, metrics)
+    }
+
+    /// Removes a retiring thread's stealer and metrics so the pool stops
+    /// counting or stealing from it.
+    fn deregister_worker(&self, metrics: &Arc<WorkerMetrics>) {
+        self.workers
+            .write()
+            .expect("blocking: workers lock poisoned")
+            .retain(|w| !Arc::ptr_eq(&w.metrics, metrics));
+    }
+
+    /// The number of worker threads currently registered with the pool.
+    fn live_threads(&self) -> usize {
+        self.workers
+            .read()
+            .expect("blocking: workers lock poisoned")
+            .len()
+    }
+
+    /// A point-in-time clone of every registered worker's [Stealer], for
+    /// [`worker::find_task`] to steal from.
+    fn stealers(&self) -> Vec<Stealer<LightProc>> {
+        self.workers
+            .read()
+            .expect("blocking: workers lock poisoned")
+            .iter()
+            .map(|w| w.stealer.clone())
+            .collect()
+    }
+
+    /// Takes a point-in-time snapshot of this pool's scheduling metrics.
+    pub(crate) fn metrics(&self) -> SchedulerMetrics {
+        let workers = self
+            .workers
+            .read()
+            .expect("blocking: workers lock poisoned");
+
+        SchedulerMetrics {
+            tasks_scheduled: self.tasks_scheduled.load(Ordering::Relaxed),
+            live_threads: workers.len(),
+            injector_depth: self.injector.len(),
+            workers: workers.iter().map(|w| w.metrics.snapshot()).collect(),
+        }
+    }
 }
 
-struct BlockingRunner {}
+struct BlockingRunner {
+    pool: Arc<BlockingPool>,
+}
 
 impl DynamicRunner for BlockingRunner {
-    fn run_static(&self, park_timeout: Duration) -> ! {
+    // The pool's own `PoolBuilder::park_timeout` takes precedence over
+    // whatever `DynamicPoolManager` passes in here, so a `PoolBuilder`-tuned
+    // pool's static threads actually honor the configured value.
+    fn run_static(&self, _park_timeout: Duration) -> ! {
         #[cfg(feature = "runtime-tokio")]
         {
             let thread_runtime = runtime::Builder::new_multi_thread()
                 .enable_all()
                 .build()
                 .expect("static thread: couldn't spawn tokio runtime");
-            thread_runtime.block_on(async move { self._static_loop(park_timeout) })
+            thread_runtime.block_on(async move { self._static_loop() })
         }
         #[cfg(not(feature = "runtime-tokio"))]
         {
-            self._static_loop(park_timeout)
+            self._static_loop()
         }
     }
-    fn run_dynamic(&self, parker: &dyn Fn()) -> ! {
+    fn run_dynamic(&self, parker: &dyn Fn()) {
         #[cfg(feature = "runtime-tokio")]
         {
             let thread_runtime = runtime::Builder::new_multi_thread()
@@ -87,88 +266,201 @@ impl DynamicRunner for BlockingRunner {
 }
 
 impl BlockingRunner {
-    fn _static_loop(&self, park_timeout: Duration) -> ! {
+    /// Installs a fresh local queue, metrics counters and display name for
+    /// the calling thread, registering both with the pool.
+    fn install_worker(&self) {
+        let (local, metrics) = self.pool.register_worker();
+        worker::set_local_queue(local);
+        worker::set_local_metrics(metrics);
+        worker::set_local_name(format!(
+            "{}-{:?}",
+            self.pool.config.effective_thread_name_prefix(),
+            thread::current().id()
+        ));
+
+        if let Some(after_start) = self.pool.config.after_start_hook() {
+            after_start();
+        }
+    }
+
+    /// Records a park and snapshots the calling thread's local queue depth,
+    /// meant to be called right before a worker parks with nothing left to
+    /// run.
+    fn note_parking(&self) {
+        worker::with_local_metrics(|m| {
+            m.record_park();
+            m.set_local_queue_depth(worker::local_queue_len().unwrap_or(0) as i64);
+        });
+    }
+
+    /// Removes this thread's stealer and metrics from the pool, releases its
+    /// claim on [`BlockingPool::reserved_threads`], and runs the
+    /// `before_stop` hook, right before a retiring thread exits.
+    fn retire_worker(&self) {
+        if let Some(metrics) = worker::local_metrics() {
+            self.pool.deregister_worker(&metrics);
+        }
+        self.pool.release_thread();
+        if let Some(before_stop) = self.pool.config.before_stop_hook() {
+            before_stop();
+        }
+    }
+
+    // Static threads poll on `park_timeout` alone; `effective_throttle` only
+    // governs dynamic threads via `_dynamic_loop`/`_throttled_loop`.
+    fn _static_loop(&self) -> ! {
+        self.install_worker();
+        let park_timeout = self.pool.config.effective_park_timeout();
         loop {
-            while let Ok(task) = POOL.receiver.recv_timeout(THREAD_RECV_TIMEOUT) {
-                trace!("static thread: running task");
-                task.run();
+            let stealers = self.pool.stealers();
+            while let Some(task) = worker::find_task(&self.pool.injector, &stealers) {
+                trace!("{}: running task", worker::local_name());
+                worker::run_task(task);
             }
 
-            trace!("static: empty queue, parking with timeout");
+            trace!("{}: empty queue, parking with timeout", worker::local_name());
+            self.note_parking();
             thread::park_timeout(park_timeout);
         }
     }
-    fn _dynamic_loop(&self, parker: &dyn Fn()) -> ! {
+
+    /// Runs tasks until retired: a dynamic thread above the low watermark
+    /// that goes [`PoolBuilder::keep_alive`] without a task to run exits
+    /// cleanly, shrinking the pool back toward the watermark.
+    fn _dynamic_loop(&self, parker: &dyn Fn()) {
+        self.install_worker();
+
+        if let Some(quantum) = self.pool.config.effective_throttle() {
+            return self._throttled_loop(quantum);
+        }
+
+        let keep_alive = self.pool.config.effective_keep_alive();
+        let mut idle_since = Instant::now();
         loop {
-            while let Ok(task) = POOL.receiver.recv_timeout(THREAD_RECV_TIMEOUT) {
-                trace!("dynamic thread: running task");
-                task.run();
+            let stealers = self.pool.stealers();
+            let mut ran_any = false;
+            while let Some(task) = worker::find_task(&self.pool.injector, &stealers) {
+                trace!("{}: running task", worker::local_name());
+                worker::run_task(task);
+                ran_any = true;
             }
-            trace!(
-                "dynamic thread: parking - {:?}",
-                std::thread::current().id()
-            );
+
+            if ran_any {
+                idle_since = Instant::now();
+            } else if idle_since.elapsed() >= keep_alive
+                && self.pool.live_threads() > self.pool.config.effective_low_watermark() as usize
+            {
+                trace!("{}: idle past keep_alive, retiring", worker::local_name());
+                self.retire_worker();
+                return;
+            }
+
+            trace!("{}: parking", worker::local_name());
+            self.note_parking();
             parker();
         }
     }
+
+    /// Batches polling into fixed-size time quanta instead of waking up for
+    /// every single incoming task: drains everything currently queued, then
+    /// parks for whatever remains of the quantum before draining again.
+    /// Like [`Self::_dynamic_loop`], retires once idle past `keep_alive`.
+    fn _throttled_loop(&self, quantum: Duration) {
+        let keep_alive = self.pool.config.effective_keep_alive();
+        let mut idle_since = Instant::now();
+        let mut next_wakeup = Instant::now() + quantum;
+        loop {
+            let stealers = self.pool.stealers();
+            let mut ran_any = false;
+            while let Some(task) = worker::find_task(&self.pool.injector, &stealers) {
+                trace!("{}: running task", worker::local_name());
+                worker::run_task(task);
+                ran_any = true;
+            }
+
+            if ran_any {
+                idle_since = Instant::now();
+            } else if idle_since.elapsed() >= keep_alive
+                && self.pool.live_threads() > self.pool.config.effective_low_watermark() as usize
+            {
+                trace!("{}: idle past keep_alive, retiring", worker::local_name());
+                self.retire_worker();
+                return;
+            }
+
+            let now = Instant::now();
+            if let Some(remaining) = next_wakeup.checked_duration_since(now) {
+                self.note_parking();
+                thread::park_timeout(remaining);
+            }
+            next_wakeup = next_wakeup.max(now) + quantum;
+        }
+    }
     fn _standalone(&self) {
-        while let Ok(task) = POOL.receiver.recv_timeout(THREAD_RECV_TIMEOUT) {
-            task.run();
+        self.install_worker();
+        let stealers = self.pool.stealers();
+        while let Some(task) = worker::find_task(&self.pool.injector, &stealers) {
+            worker::run_task(task);
         }
-        trace!("standalone thread: quitting.");
+        self.retire_worker();
+        trace!("{}: quitting", worker::local_name());
     }
 }
-/// Pool interface between the scheduler and thread pool
-struct Pool {
-    sender: Sender<LightProc>,
-    receiver: Receiver<LightProc>,
-}
 
-static DYNAMIC_POOL_MANAGER: OnceCell<DynamicPoolManager> = OnceCell::new();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread::sleep;
 
-static POOL: Lazy<Pool> = Lazy::new(|| {
-    let runner = Arc::new(BlockingRunner {});
+    #[test]
+    fn burst_of_spawn_blocking_never_exceeds_max_threads() {
+        let pool = BlockingPool::build(
+            PoolBuilder::new()
+                .low_watermark(1)
+                .max_threads(4)
+                .keep_alive(Duration::from_millis(50)),
+        );
 
-    DYNAMIC_POOL_MANAGER
-        .set(DynamicPoolManager::new(*low_watermark() as usize, runner))
-        .expect("couldn't create dynamic pool manager");
-    DYNAMIC_POOL_MANAGER
-        .get()
-        .expect("couldn't get static pool manager")
-        .initialize();
-
-    let (sender, receiver) = unbounded();
-    Pool { sender, receiver }
-});
+        for _ in 0..64 {
+            pool.spawn_blocking(
+                async {
+                    sleep(Duration::from_millis(20));
+                },
+                ProcStack::default(),
+            );
+        }
 
-/// Enqueues work, attempting to send to the thread pool in a
-/// nonblocking way and spinning up needed amount of threads
-/// based on the previous statistics without relying on
-/// if there is not a thread ready to accept the work or not.
-fn schedule(t: LightProc) {
-    if let Err(err) = POOL.sender.try_send(t) {
-        // We were not able to send to the channel without
-        // blocking.
-        POOL.sender.send(err.into_inner()).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while Instant::now() < deadline {
+            assert!(
+                pool.metrics().live_threads <= 4,
+                "pool scaled past its configured max_threads"
+            );
+            sleep(Duration::from_millis(10));
+        }
     }
 
-    // Add up for every incoming scheduled task
-    DYNAMIC_POOL_MANAGER.get().unwrap().increment_frequency();
-}
+    #[test]
+    fn idle_dynamic_thread_retires_past_keep_alive() {
+        let pool = BlockingPool::build(
+            PoolBuilder::new()
+                .low_watermark(1)
+                .keep_alive(Duration::from_millis(50)),
+        );
 
-///
-/// Low watermark value, defines the bare minimum of the pool.
-/// Spawns initial thread set.
-/// Can be configurable with env var `BASTION_BLOCKING_THREADS` at runtime.
-#[inline]
-fn low_watermark() -> &'static u64 {
-    lazy_static! {
-        static ref LOW_WATERMARK: u64 = {
-            env::var_os("BASTION_BLOCKING_THREADS")
-                .map(|x| x.to_str().unwrap().parse::<u64>().unwrap())
-                .unwrap_or(DEFAULT_LOW_WATERMARK)
-        };
-    }
+        for _ in 0..8 {
+            pool.spawn_blocking(async {}, ProcStack::default());
+        }
+        sleep(Duration::from_millis(100));
+        let scaled_up = pool.metrics().live_threads;
 
-    &*LOW_WATERMARK
+        // Once idle past `keep_alive`, every dynamic thread above the low
+        // watermark should have retired, shrinking back toward it.
+        sleep(Duration::from_millis(500));
+        assert!(
+            pool.metrics().live_threads <= scaled_up,
+            "pool didn't shrink back down after sitting idle"
+        );
+        assert!(pool.metrics().live_threads >= 1);
+    }
 }
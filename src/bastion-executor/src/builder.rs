@@ -0,0 +1,234 @@
+//!
+//! Programmatic configuration for pools.
+//!
+//! The pools' only knob used to be the `BASTION_BLOCKING_THREADS` env var,
+//! which is parsed once into a process-wide low watermark and panics on
+//! malformed input. [`PoolBuilder`]
+//! lets an application configure a pool directly; any option left unset
+//! falls back to the env var, and a malformed env var falls back to the
+//! built-in default instead of panicking.
+
+use std::env;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// If low watermark isn't configured this is the default scaler value.
+/// This value is used for the heuristics of the scaler
+pub(crate) const DEFAULT_LOW_WATERMARK: u64 = 2;
+
+/// Default park timeout for a pool's static worker threads.
+pub(crate) const DEFAULT_PARK_TIMEOUT: Duration = Duration::from_millis(10);
+
+/// Default prefix used to name a pool's worker threads.
+pub(crate) const DEFAULT_THREAD_NAME_PREFIX: &str = "bastion-pool";
+
+/// Default idle duration a dynamic worker thread waits before retiring, once
+/// above the low watermark.
+pub(crate) const DEFAULT_KEEP_ALIVE: Duration = Duration::from_secs(10);
+
+/// A lifecycle callback invoked on every worker thread a pool spawns.
+pub type ThreadCallback = Arc<dyn Fn() + Send + Sync>;
+
+/// Configures the size and behavior of a [`crate::pool::AsyncPool`] or
+/// [`crate::blocking::BlockingPool`], in place of tuning them through the
+/// `BASTION_BLOCKING_THREADS` env var.
+///
+/// Any field left unset falls back to a built-in default, or, for the low
+/// watermark, to the env var first.
+#[derive(Clone, Default)]
+pub struct PoolBuilder {
+    low_watermark: Option<u64>,
+    max_threads: Option<u64>,
+    park_timeout: Option<Duration>,
+    throttling_duration: Option<Duration>,
+    thread_name_prefix: Option<String>,
+    keep_alive: Option<Duration>,
+    after_start: Option<ThreadCallback>,
+    before_stop: Option<ThreadCallback>,
+}
+
+impl PoolBuilder {
+    /// Creates a builder with every option defaulted.
+    pub fn new() -> Self {
+        PoolBuilder::default()
+    }
+
+    /// Sets the minimum number of worker threads the pool keeps alive.
+    ///
+    /// Defaults to `BASTION_BLOCKING_THREADS` if unset, or
+    /// [`DEFAULT_LOW_WATERMARK`] if that env var is unset or malformed.
+    pub fn low_watermark(mut self, low_watermark: u64) -> Self {
+        self.low_watermark = Some(low_watermark);
+        self
+    }
+
+    /// Sets a hard ceiling on the number of worker threads the pool may
+    /// scale up to. Unset means unbounded.
+    ///
+    /// Only [`crate::blocking::BlockingPool`] enforces this ceiling;
+    /// [`crate::pool::AsyncPool`] ignores this setting.
+    pub fn max_threads(mut self, max_threads: u64) -> Self {
+        self.max_threads = Some(max_threads);
+        self
+    }
+
+    /// Sets how long a static worker thread parks before re-checking its
+    /// queues once they're empty.
+    pub fn park_timeout(mut self, park_timeout: Duration) -> Self {
+        self.park_timeout = Some(park_timeout);
+        self
+    }
+
+    /// Batches polling into fixed-size time quanta of `quantum` instead of
+    /// waking a dynamic worker thread up for every single incoming task.
+    ///
+    /// Only dynamic worker threads throttle this way; static threads (the
+    /// low-watermark batch) keep polling on [`Self::park_timeout`]'s cadence.
+    pub fn throttling_duration(mut self, quantum: Duration) -> Self {
+        self.throttling_duration = Some(quantum);
+        self
+    }
+
+    /// Sets the prefix of the name each worker thread logs under, followed
+    /// by its thread id (e.g. `"my-pool"` gives `"my-pool-ThreadId(7)"`).
+    pub fn thread_name_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.thread_name_prefix = Some(prefix.into());
+        self
+    }
+
+    /// Sets how long a dynamic worker thread may sit idle, once above the
+    /// low watermark, before it retires and the pool shrinks back toward it.
+    ///
+    /// Only [`crate::blocking::BlockingPool`] currently retires idle
+    /// threads; [`crate::pool::AsyncPool`] ignores this setting.
+    pub fn keep_alive(mut self, keep_alive: Duration) -> Self {
+        self.keep_alive = Some(keep_alive);
+        self
+    }
+
+    /// Sets a callback run on every worker thread right after it starts.
+    pub fn after_start<F>(mut self, f: F) -> Self
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        self.after_start = Some(Arc::new(f));
+        self
+    }
+
+    /// Sets a callback run on every worker thread right before it stops.
+    pub fn before_stop<F>(mut self, f: F) -> Self
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        self.before_stop = Some(Arc::new(f));
+        self
+    }
+
+    /// The resolved low watermark: the configured value, or
+    /// `BASTION_BLOCKING_THREADS`, or [`DEFAULT_LOW_WATERMARK`].
+    pub(crate) fn effective_low_watermark(&self) -> u64 {
+        self.low_watermark.unwrap_or_else(low_watermark_from_env)
+    }
+
+    /// The configured hard ceiling on live worker threads, if any.
+    pub(crate) fn effective_max_threads(&self) -> Option<u64> {
+        self.max_threads
+    }
+
+    /// The resolved park timeout for static worker threads.
+    pub(crate) fn effective_park_timeout(&self) -> Duration {
+        self.park_timeout.unwrap_or(DEFAULT_PARK_TIMEOUT)
+    }
+
+    /// The configured throttling quantum for dynamic worker threads, if any.
+    /// Static worker threads never consult this.
+    pub(crate) fn effective_throttle(&self) -> Option<Duration> {
+        self.throttling_duration
+    }
+
+    /// The resolved thread name prefix.
+    pub(crate) fn effective_thread_name_prefix(&self) -> &str {
+        self.thread_name_prefix
+            .as_deref()
+            .unwrap_or(DEFAULT_THREAD_NAME_PREFIX)
+    }
+
+    /// The resolved idle timeout before a dynamic worker thread above the
+    /// low watermark retires.
+    pub(crate) fn effective_keep_alive(&self) -> Duration {
+        self.keep_alive.unwrap_or(DEFAULT_KEEP_ALIVE)
+    }
+
+    /// The configured `after_start` callback, if any.
+    pub(crate) fn after_start_hook(&self) -> Option<&ThreadCallback> {
+        self.after_start.as_ref()
+    }
+
+    /// The configured `before_stop` callback, if any.
+    pub(crate) fn before_stop_hook(&self) -> Option<&ThreadCallback> {
+        self.before_stop.as_ref()
+    }
+}
+
+impl fmt::Debug for PoolBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PoolBuilder")
+            .field("low_watermark", &self.low_watermark)
+            .field("max_threads", &self.max_threads)
+            .field("park_timeout", &self.park_timeout)
+            .field("throttling_duration", &self.throttling_duration)
+            .field("thread_name_prefix", &self.thread_name_prefix)
+            .field("keep_alive", &self.keep_alive)
+            .field("after_start", &self.after_start.is_some())
+            .field("before_stop", &self.before_stop.is_some())
+            .finish()
+    }
+}
+
+/// Reads `BASTION_BLOCKING_THREADS`, falling back to
+/// [`DEFAULT_LOW_WATERMARK`] if it's unset *or* malformed, rather than
+/// panicking.
+fn low_watermark_from_env() -> u64 {
+    env::var_os("BASTION_BLOCKING_THREADS")
+        .and_then(|v| v.to_str().map(str::to_owned))
+        .and_then(|s| s.parse::<u64>().ok())
+        .unwrap_or(DEFAULT_LOW_WATERMARK)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    // `BASTION_BLOCKING_THREADS` is process-wide, so these hold `ENV_LOCK`
+    // to run serially and avoid racing each other.
+    static ENV_LOCK: Mutex<()> = Mutex::new(());
+
+    #[test]
+    fn malformed_env_var_falls_back_to_default() {
+        let _guard = ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        env::set_var("BASTION_BLOCKING_THREADS", "not-a-number");
+        assert_eq!(low_watermark_from_env(), DEFAULT_LOW_WATERMARK);
+
+        env::remove_var("BASTION_BLOCKING_THREADS");
+        assert_eq!(low_watermark_from_env(), DEFAULT_LOW_WATERMARK);
+    }
+
+    #[test]
+    fn well_formed_env_var_is_used() {
+        let _guard = ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        env::set_var("BASTION_BLOCKING_THREADS", "7");
+        assert_eq!(low_watermark_from_env(), 7);
+        env::remove_var("BASTION_BLOCKING_THREADS");
+    }
+
+    #[test]
+    fn explicit_low_watermark_overrides_env() {
+        let _guard = ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        env::set_var("BASTION_BLOCKING_THREADS", "7");
+        let builder = PoolBuilder::new().low_watermark(3);
+        assert_eq!(builder.effective_low_watermark(), 3);
+        env::remove_var("BASTION_BLOCKING_THREADS");
+    }
+}
@@ -0,0 +1,262 @@
+//!
+//! Multiple independent executor instances.
+//!
+//! The process-wide [`spawn`]/[`spawn_blocking`]/[`get`] functions are a thin
+//! wrapper around a single, lazily-created default [`Executor`]. Library
+//! embedders that want isolated pools instead — say, one bounded pool for
+//! latency-sensitive work and another for bulk work — can create as many
+//! [`Executor`]s as they like, each with its own injector queues, worker
+//! threads and [`crate::thread_manager::DynamicPoolManager`]. Use
+//! [`Executor::builder`] to tune one before it starts.
+
+use crate::blocking::BlockingPool;
+use crate::builder::PoolBuilder;
+use crate::metrics::ExecutorMetrics;
+use crate::pool::AsyncPool;
+use lightproc::proc_stack::ProcStack;
+use lightproc::recoverable_handle::RecoverableHandle;
+use once_cell::sync::Lazy;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// An isolated pair of lightweight-process and blocking thread pools.
+///
+/// Unlike the process-wide [`spawn`]/[`spawn_blocking`] functions, each
+/// `Executor` owns its own worker threads and scaling manager, so several can
+/// coexist in the same program with independent tuning.
+pub struct Executor {
+    async_pool: Arc<AsyncPool>,
+    blocking_pool: Arc<BlockingPool>,
+}
+
+impl Executor {
+    /// Creates a new `Executor`, spinning up its own isolated set of worker
+    /// threads for both the async and blocking pools.
+    pub fn new() -> Executor {
+        Executor {
+            async_pool: AsyncPool::new(),
+            blocking_pool: BlockingPool::new(),
+        }
+    }
+
+    /// Creates a new `Executor` whose dynamic worker threads batch-poll for
+    /// ready tasks every `quantum` instead of waking up for each one, trading
+    /// a bounded amount of latency for far fewer wakeups under bursty,
+    /// many-short-tasks workloads.
+    pub fn with_throttling(quantum: Duration) -> Executor {
+        ExecutorBuilder::new().throttling_duration(quantum).build()
+    }
+
+    /// Starts configuring an `Executor` with a [`PoolBuilder`], for tuning
+    /// thread counts, park timeouts, thread naming and lifecycle hooks.
+    pub fn builder() -> ExecutorBuilder {
+        ExecutorBuilder::new()
+    }
+
+    ///
+    /// Spawn a process (which contains future + process stack) onto this executor.
+    pub fn spawn<F, T>(&self, future: F, stack: ProcStack) -> RecoverableHandle<T>
+    where
+        F: Future<Output = T> + Send + 'static,
+        T: Send + 'static,
+    {
+        self.async_pool.spawn(future, stack)
+    }
+
+    /// Spawns a blocking task onto this executor's dedicated blocking pool.
+    pub fn spawn_blocking<F, R>(&self, future: F, stack: ProcStack) -> RecoverableHandle<R>
+    where
+        F: Future<Output = R> + Send + 'static,
+        R: Send + 'static,
+    {
+        self.blocking_pool.spawn_blocking(future, stack)
+    }
+
+    /// Takes a point-in-time snapshot of this executor's async and blocking
+    /// pool metrics, for driving autoscaling dashboards or alerts.
+    pub fn metrics(&self) -> ExecutorMetrics {
+        ExecutorMetrics {
+            async_pool: self.async_pool.metrics(),
+            blocking_pool: self.blocking_pool.metrics(),
+        }
+    }
+}
+
+impl Default for Executor {
+    fn default() -> Self {
+        Executor::new()
+    }
+}
+
+/// Configures an [`Executor`] before spinning up its worker threads.
+///
+/// Wraps a single [`PoolBuilder`], applying it identically to both the
+/// async and blocking pools — there's currently no way to tune them
+/// independently through this builder.
+#[derive(Clone, Default)]
+pub struct ExecutorBuilder(PoolBuilder);
+
+impl ExecutorBuilder {
+    /// Creates a builder with every option defaulted.
+    pub fn new() -> Self {
+        ExecutorBuilder::default()
+    }
+
+    /// Sets the minimum number of worker threads each pool keeps alive.
+    pub fn low_watermark(mut self, low_watermark: u64) -> Self {
+        self.0 = self.0.low_watermark(low_watermark);
+        self
+    }
+
+    /// Sets a hard ceiling on the number of worker threads the blocking pool
+    /// may scale up to. Unset means unbounded. The async pool ignores this
+    /// setting.
+    pub fn max_threads(mut self, max_threads: u64) -> Self {
+        self.0 = self.0.max_threads(max_threads);
+        self
+    }
+
+    /// Sets how long a static worker thread parks before re-checking its
+    /// queues once they're empty.
+    pub fn park_timeout(mut self, park_timeout: Duration) -> Self {
+        self.0 = self.0.park_timeout(park_timeout);
+        self
+    }
+
+    /// Batches polling into fixed-size time quanta of `quantum` instead of
+    /// waking a dynamic worker thread up for every single incoming task.
+    ///
+    /// Only dynamic worker threads throttle this way; static threads (the
+    /// low-watermark batch) keep polling on [`Self::park_timeout`]'s cadence.
+    pub fn throttling_duration(mut self, quantum: Duration) -> Self {
+        self.0 = self.0.throttling_duration(quantum);
+        self
+    }
+
+    /// Sets the prefix used to name every thread the pools spawn.
+    pub fn thread_name_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.0 = self.0.thread_name_prefix(prefix);
+        self
+    }
+
+    /// Sets how long a dynamic worker thread may sit idle, once above the
+    /// low watermark, before it retires and the pool shrinks back toward it.
+    ///
+    /// Only the blocking pool currently retires idle threads; the async pool
+    /// ignores this setting.
+    pub fn keep_alive(mut self, keep_alive: Duration) -> Self {
+        self.0 = self.0.keep_alive(keep_alive);
+        self
+    }
+
+    /// Sets a callback run on every worker thread right after it starts.
+    pub fn after_start<F>(mut self, f: F) -> Self
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        self.0 = self.0.after_start(f);
+        self
+    }
+
+    /// Sets a callback run on every worker thread right before it stops.
+    pub fn before_stop<F>(mut self, f: F) -> Self
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        self.0 = self.0.before_stop(f);
+        self
+    }
+
+    /// Builds the `Executor`, spinning up its isolated set of worker threads.
+    pub fn build(self) -> Executor {
+        Executor {
+            async_pool: AsyncPool::build(self.0.clone()),
+            blocking_pool: BlockingPool::build(self.0),
+        }
+    }
+}
+
+static DEFAULT: Lazy<Executor> = Lazy::new(Executor::new);
+
+///
+/// Acquire the process-wide default [`Executor`].
+#[inline]
+pub fn get() -> &'static Executor {
+    &DEFAULT
+}
+
+///
+/// Spawn a process (which contains future + process stack) onto the process-wide default executor.
+///
+/// # Example
+/// ```rust
+/// use bastion_executor::prelude::*;
+/// use lightproc::prelude::*;
+///
+/// let pid = 1;
+/// let stack = ProcStack::default().with_pid(pid);
+///
+/// let handle = spawn(
+///     async {
+///         panic!("test");
+///     },
+///     stack.clone(),
+/// );
+///
+/// run(
+///     async {
+///         handle.await;
+///     },
+///     stack.clone(),
+/// );
+/// ```
+pub fn spawn<F, T>(future: F, stack: ProcStack) -> RecoverableHandle<T>
+where
+    F: Future<Output = T> + Send + 'static,
+    T: Send + 'static,
+{
+    DEFAULT.spawn(future, stack)
+}
+
+/// Spawns a blocking task onto the process-wide default executor.
+///
+/// The task will be spawned onto a thread pool specifically dedicated to blocking tasks.
+pub fn spawn_blocking<F, R>(future: F, stack: ProcStack) -> RecoverableHandle<R>
+where
+    F: Future<Output = R> + Send + 'static,
+    R: Send + 'static,
+{
+    DEFAULT.spawn_blocking(future, stack)
+}
+
+/// Takes a point-in-time snapshot of the process-wide default executor's
+/// metrics.
+pub fn metrics() -> ExecutorMetrics {
+    DEFAULT.metrics()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread::sleep;
+    use std::time::Duration;
+
+    #[test]
+    fn two_executors_are_independently_isolated() {
+        let a = Executor::new();
+        let b = Executor::new();
+
+        for _ in 0..5 {
+            a.spawn(async {}, ProcStack::default());
+        }
+        sleep(Duration::from_millis(50));
+
+        assert_eq!(a.metrics().async_pool.tasks_scheduled, 5);
+        assert_eq!(
+            b.metrics().async_pool.tasks_scheduled,
+            0,
+            "spawning on one Executor must not be visible on another"
+        );
+    }
+}
@@ -0,0 +1,96 @@
+//!
+//! Runtime metrics for the pools.
+//!
+//! Each worker thread maintains its own [`WorkerMetrics`], updated with
+//! relaxed atomics so the hot `task.run()` path pays almost nothing.
+//! Snapshots are only assembled on demand, when a pool's
+//! [`SchedulerMetrics`] is actually read.
+
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+
+/// Per-worker counters, updated with relaxed atomics on the hot path.
+#[derive(Debug, Default)]
+pub(crate) struct WorkerMetrics {
+    tasks_polled: AtomicU64,
+    steals_attempted: AtomicU64,
+    steals_succeeded: AtomicU64,
+    parks: AtomicU64,
+    local_queue_depth: AtomicI64,
+}
+
+impl WorkerMetrics {
+    pub(crate) fn record_task_polled(&self) {
+        self.tasks_polled.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_steal_attempt(&self) {
+        self.steals_attempted.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_steal_success(&self) {
+        self.steals_succeeded.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_park(&self) {
+        self.parks.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn set_local_queue_depth(&self, depth: i64) {
+        self.local_queue_depth.store(depth, Ordering::Relaxed);
+    }
+
+    /// Snapshots this worker's counters into a plain, non-atomic struct.
+    pub(crate) fn snapshot(&self) -> WorkerMetricsSnapshot {
+        WorkerMetricsSnapshot {
+            tasks_polled: self.tasks_polled.load(Ordering::Relaxed),
+            steals_attempted: self.steals_attempted.load(Ordering::Relaxed),
+            steals_succeeded: self.steals_succeeded.load(Ordering::Relaxed),
+            parks: self.parks.load(Ordering::Relaxed),
+            local_queue_depth: self.local_queue_depth.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// A point-in-time copy of one worker thread's counters.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WorkerMetricsSnapshot {
+    /// Tasks this worker has run, whether popped from its own local queue or
+    /// stolen from the injector/another worker.
+    pub tasks_polled: u64,
+    /// Times this worker fell through to stealing because its local queue
+    /// was empty.
+    pub steals_attempted: u64,
+    /// Of `steals_attempted`, how many actually yielded a task.
+    pub steals_succeeded: u64,
+    /// Times this worker parked after finding nothing to run.
+    pub parks: u64,
+    /// Depth of this worker's local queue, as of its last park.
+    pub local_queue_depth: i64,
+}
+
+/// A point-in-time snapshot of one pool's health: how much work it has seen
+/// and how its worker threads are spending their time.
+#[derive(Debug, Clone, Default)]
+pub struct SchedulerMetrics {
+    /// Total tasks handed to the pool's `schedule` function since it was
+    /// created.
+    pub tasks_scheduled: u64,
+    /// Number of worker threads currently registered with the pool (i.e. that
+    /// have reached [`crate::worker::set_local_metrics`] and not yet retired).
+    pub live_threads: usize,
+    /// Number of tasks currently sitting in the pool's shared injector
+    /// queue, waiting to be stolen by a worker.
+    pub injector_depth: usize,
+    /// Per-worker counters, one entry per live worker thread.
+    pub workers: Vec<WorkerMetricsSnapshot>,
+}
+
+/// A point-in-time snapshot of an [`crate::executor::Executor`]'s health:
+/// its async pool and its dedicated blocking pool.
+#[derive(Debug, Clone, Default)]
+pub struct ExecutorMetrics {
+    /// Metrics for the lightweight-process pool.
+    pub async_pool: SchedulerMetrics,
+    /// Metrics for the blocking-task pool.
+    pub blocking_pool: SchedulerMetrics,
+}
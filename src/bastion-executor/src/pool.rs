@@ -1,85 +1,105 @@
 //!
 //! Pool of threads to run lightweight processes
 //!
-//! We spawn futures onto the pool with [spawn] method of global run queue or
-//! with corresponding [Worker]'s spawn method.
+//! An [`AsyncPool`] owns its own injector queue, its own worker-local steal
+//! queues and its own [`DynamicPoolManager`]; [`crate::executor::Executor`]
+//! composes one together with a [`crate::blocking::BlockingPool`] to form an
+//! independently-tunable executor. Most callers don't need this type
+//! directly — see [`crate::executor::spawn`] and [`crate::executor::get`]
+//! for the process-wide default pool.
 
+use crate::builder::PoolBuilder;
+use crate::metrics::{SchedulerMetrics, WorkerMetrics};
 use crate::thread_manager::{DynamicPoolManager, DynamicRunner};
-use crate::worker;
-use crossbeam_channel::{unbounded, Receiver, Sender};
-use lazy_static::lazy_static;
+use crate::worker::{self, WorkerEntry};
+use crossbeam_deque::{Injector, Stealer, Worker as Deque};
 use lightproc::lightproc::LightProc;
 use lightproc::proc_stack::ProcStack;
 use lightproc::recoverable_handle::RecoverableHandle;
-use once_cell::sync::{Lazy, OnceCell};
+use once_cell::sync::OnceCell;
 use std::future::Future;
-use std::iter::Iterator;
-use std::sync::Arc;
-use std::time::Duration;
-use std::{env, thread};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use std::thread;
 #[cfg(feature = "runtime-tokio")]
 use tokio::runtime;
 use tracing::trace;
 
 ///
-/// Spawn a process (which contains future + process stack) onto the executor from the global level.
+/// Spawn a process (which contains future + process stack) onto the process-wide default executor.
 ///
-/// # Example
-/// ```rust
-/// use bastion_executor::prelude::*;
-/// use lightproc::prelude::*;
-///
-/// let pid = 1;
-/// let stack = ProcStack::default().with_pid(pid);
-///
-/// let handle = spawn(
-///     async {
-///         panic!("test");
-///     },
-///     stack.clone(),
-/// );
-///
-/// run(
-///     async {
-///         handle.await;
-///     },
-///     stack.clone(),
-/// );
-/// ```
+/// Kept for backwards compatibility; prefer [`crate::executor::spawn`].
 pub fn spawn<F, T>(future: F, stack: ProcStack) -> RecoverableHandle<T>
 where
     F: Future<Output = T> + Send + 'static,
     T: Send + 'static,
 {
-    let (task, handle) = LightProc::recoverable(future, worker::schedule, stack);
-    task.schedule();
-    handle
+    crate::executor::spawn(future, stack)
 }
 
-/// Spawns a blocking task.
+/// Spawns a blocking task onto the process-wide default executor.
 ///
-/// The task will be spawned onto a thread pool specifically dedicated to blocking tasks.
+/// Kept for backwards compatibility; prefer [`crate::executor::spawn_blocking`].
 pub fn spawn_blocking<F, R>(future: F, stack: ProcStack) -> RecoverableHandle<R>
 where
     F: Future<Output = R> + Send + 'static,
     R: Send + 'static,
 {
-    let (task, handle) = LightProc::recoverable(future, schedule, stack);
-    task.schedule();
-    handle
+    crate::executor::spawn_blocking(future, stack)
 }
 
 ///
-/// Acquire the static Pool reference
+/// Acquire the process-wide default executor.
+///
+/// Kept for backwards compatibility; prefer [`crate::executor::get`].
 #[inline]
-pub fn get() -> &'static Pool {
-    &*POOL
+pub fn get() -> &'static crate::executor::Executor {
+    crate::executor::get()
+}
+
+/// An isolated lightweight-process pool: its own injector queue, its own
+/// worker-local steal queues, and its own [`DynamicPoolManager`].
+pub(crate) struct AsyncPool {
+    injector: Injector<LightProc>,
+    workers: RwLock<Vec<WorkerEntry>>,
+    tasks_scheduled: AtomicU64,
+    manager: OnceCell<Arc<DynamicPoolManager>>,
+    config: PoolBuilder,
 }
 
-impl Pool {
-    ///
-    /// Spawn a process (which contains future + process stack) onto the executor via [Pool] interface.
-    pub fn spawn<F, T>(&self, future: F, stack: ProcStack) -> RecoverableHandle<T>
+impl AsyncPool {
+    /// Creates a new pool with the default configuration, spinning up its
+    /// own isolated set of worker threads.
+    pub(crate) fn new() -> Arc<AsyncPool> {
+        Self::build(PoolBuilder::new())
+    }
+
+    /// Creates a new pool configured by `config`, spinning up its own
+    /// isolated set of worker threads.
+    pub(crate) fn build(config: PoolBuilder) -> Arc<AsyncPool> {
+        let pool = Arc::new(AsyncPool {
+            injector: Injector::new(),
+            workers: RwLock::new(Vec::new()),
+            tasks_scheduled: AtomicU64::new(0),
+            manager: OnceCell::new(),
+            config,
+        });
+
+        let runner = Arc::new(AsyncRunner {
+            pool: pool.clone(),
+        });
+        let manager = Arc::new(DynamicPoolManager::new(pool.config.clone(), runner));
+        manager.initialize();
+        pool.manager
+            .set(manager)
+            .expect("pool manager already initialized");
+
+        pool
+    }
+
+    /// Spawn a process (which contains future + process stack) onto this pool.
+    pub(crate) fn spawn<F, T>(self: &Arc<Self>, future: F, stack: ProcStack) -> RecoverableHandle<T>
     where
         F: Future<Output = T> + Send + 'static,
         T: Send + 'static,
@@ -88,72 +108,100 @@ impl Pool {
         let _child_id = stack.get_pid() as u64;
         let _parent_id = worker::get_proc_stack(|t| t.get_pid() as u64).unwrap_or(0);
 
-        let (task, handle) = LightProc::recoverable(future, worker::schedule, stack);
+        let pool = self.clone();
+        let (task, handle) = LightProc::recoverable(future, move |t| pool.schedule(t), stack);
         task.schedule();
         handle
     }
-}
 
-/// Enqueues work, attempting to send to the thread pool in a
-/// nonblocking way and spinning up needed amount of threads
-/// based on the previous statistics without relying on
-/// if there is not a thread ready to accept the work or not.
-pub(crate) fn schedule(t: LightProc) {
-    if let Err(err) = POOL.sender.try_send(t) {
-        // We were not able to send to the channel without
-        // blocking.
-        POOL.sender.send(err.into_inner()).unwrap();
-    }
-    // Add up for every incoming scheduled task
-    DYNAMIC_POOL_MANAGER.get().unwrap().increment_frequency();
-}
+    /// Enqueues work, pushing onto the calling thread's local queue if it is
+    /// one of this pool's own workers, or the shared injector otherwise, and
+    /// spinning up needed amount of threads based on the previous
+    /// statistics.
+    fn schedule(&self, t: LightProc) {
+        worker::push_local_or(t, &self.injector);
+        self.tasks_scheduled.fetch_add(1, Ordering::Relaxed);
 
-///
-/// Low watermark value, defines the bare minimum of the pool.
-/// Spawns initial thread set.
-/// Can be configurable with env var `BASTION_BLOCKING_THREADS` at runtime.
-#[inline]
-fn low_watermark() -> &'static u64 {
-    lazy_static! {
-        static ref LOW_WATERMARK: u64 = {
-            env::var_os("BASTION_BLOCKING_THREADS")
-                .map(|x| x.to_str().unwrap().parse::<u64>().unwrap())
-                .unwrap_or(DEFAULT_LOW_WATERMARK)
-        };
+        // Add up for every incoming scheduled task
+        self.manager
+            .get()
+            .expect("pool manager not initialized")
+            .increment_frequency();
     }
 
-    &*LOW_WATERMARK
-}
+    /// Creates a fresh local run queue and metrics counters for a newly
+    /// spawned worker thread, registering its [Stealer] with the pool so
+    /// other workers can steal from it.
+    fn register_worker(&self) -> (Deque<LightProc>, Arc<WorkerMetrics>) {
+        let local = Deque::new_lifo();
+        let metrics = Arc::new(WorkerMetrics::default());
+        self.workers
+            .write()
+            .expect("pool: workers lock poisoned")
+            .push(WorkerEntry {
+                stealer: local.stealer(),
+                metrics: metrics.clone(),
+            });
+        (local, metrics)
+    }
+
+    /// Removes a retiring thread's stealer and metrics so the pool stops
+    /// counting or stealing from it.
+    fn deregister_worker(&self, metrics: &Arc<WorkerMetrics>) {
+        self.workers
+            .write()
+            .expect("pool: workers lock poisoned")
+            .retain(|w| !Arc::ptr_eq(&w.metrics, metrics));
+    }
+
+    /// A point-in-time clone of every registered worker's [Stealer], for
+    /// [`worker::find_task`] to steal from without holding the pool's
+    /// workers lock across task execution.
+    fn stealers(&self) -> Vec<Stealer<LightProc>> {
+        self.workers
+            .read()
+            .expect("pool: workers lock poisoned")
+            .iter()
+            .map(|w| w.stealer.clone())
+            .collect()
+    }
 
-/// If low watermark isn't configured this is the default scaler value.
-/// This value is used for the heuristics of the scaler
-const DEFAULT_LOW_WATERMARK: u64 = 2;
+    /// Takes a point-in-time snapshot of this pool's scheduling metrics.
+    pub(crate) fn metrics(&self) -> SchedulerMetrics {
+        let workers = self.workers.read().expect("pool: workers lock poisoned");
 
-/// Pool interface between the scheduler and thread pool
-#[derive(Debug)]
-pub struct Pool {
-    sender: Sender<LightProc>,
-    receiver: Receiver<LightProc>,
+        SchedulerMetrics {
+            tasks_scheduled: self.tasks_scheduled.load(Ordering::Relaxed),
+            live_threads: workers.len(),
+            injector_depth: self.injector.len(),
+            workers: workers.iter().map(|w| w.metrics.snapshot()).collect(),
+        }
+    }
 }
 
-struct AsyncRunner {}
+struct AsyncRunner {
+    pool: Arc<AsyncPool>,
+}
 
 impl DynamicRunner for AsyncRunner {
-    fn run_static(&self, park_timeout: Duration) -> ! {
+    // The pool's own `PoolBuilder::park_timeout` takes precedence over
+    // whatever `DynamicPoolManager` passes in here, so a `PoolBuilder`-tuned
+    // pool's static threads actually honor the configured value.
+    fn run_static(&self, _park_timeout: Duration) -> ! {
         #[cfg(feature = "runtime-tokio")]
         {
             let thread_runtime = runtime::Builder::new_multi_thread()
                 .enable_all()
                 .build()
                 .expect("static thread: couldn't spawn tokio runtime");
-            thread_runtime.block_on(async move { self._static_loop(park_timeout) })
+            thread_runtime.block_on(async move { self._static_loop() })
         }
         #[cfg(not(feature = "runtime-tokio"))]
         {
-            self._static_loop(park_timeout)
+            self._static_loop()
         }
     }
-    fn run_dynamic(&self, parker: &dyn Fn()) -> ! {
+    fn run_dynamic(&self, parker: &dyn Fn()) {
         #[cfg(feature = "runtime-tokio")]
         {
             let thread_runtime = runtime::Builder::new_multi_thread()
@@ -180,56 +228,102 @@ impl DynamicRunner for AsyncRunner {
         {
             self._standalone()
         }
-        self._standalone()
     }
 }
 
 impl AsyncRunner {
-    fn _static_loop(&self, park_timeout: Duration) -> ! {
+    /// Installs a fresh local queue, metrics counters and display name for
+    /// the calling thread, registering both with the pool.
+    fn install_worker(&self) {
+        let (local, metrics) = self.pool.register_worker();
+        worker::set_local_queue(local);
+        worker::set_local_metrics(metrics);
+        worker::set_local_name(format!(
+            "{}-{:?}",
+            self.pool.config.effective_thread_name_prefix(),
+            thread::current().id()
+        ));
+
+        if let Some(after_start) = self.pool.config.after_start_hook() {
+            after_start();
+        }
+    }
+
+    /// Records a park and snapshots the calling thread's local queue depth,
+    /// meant to be called right before a worker parks with nothing left to
+    /// run.
+    fn note_parking(&self) {
+        worker::with_local_metrics(|m| {
+            m.record_park();
+            m.set_local_queue_depth(worker::local_queue_len().unwrap_or(0) as i64);
+        });
+    }
+
+    // Static threads poll on `park_timeout` alone; `effective_throttle` only
+    // governs dynamic threads via `_dynamic_loop`/`_throttled_loop`.
+    fn _static_loop(&self) -> ! {
+        self.install_worker();
+        let park_timeout = self.pool.config.effective_park_timeout();
         loop {
-            for task in &POOL.receiver {
-                trace!("static: running task");
-                task.run();
+            let stealers = self.pool.stealers();
+            while let Some(task) = worker::find_task(&self.pool.injector, &stealers) {
+                trace!("{}: running task", worker::local_name());
+                worker::run_task(task);
             }
 
-            trace!("static: empty queue, parking with timeout");
+            trace!("{}: empty queue, parking with timeout", worker::local_name());
+            self.note_parking();
             thread::park_timeout(park_timeout);
         }
     }
     fn _dynamic_loop(&self, parker: &dyn Fn()) -> ! {
+        self.install_worker();
+
+        if let Some(quantum) = self.pool.config.effective_throttle() {
+            self._throttled_loop(quantum)
+        } else {
+            loop {
+                let stealers = self.pool.stealers();
+                while let Some(task) = worker::find_task(&self.pool.injector, &stealers) {
+                    trace!("{}: running task", worker::local_name());
+                    worker::run_task(task);
+                }
+                trace!("{}: parking", worker::local_name());
+                self.note_parking();
+                parker();
+            }
+        }
+    }
+
+    /// Batches polling into fixed-size time quanta instead of waking up for
+    /// every single incoming task: drains everything currently queued, then
+    /// parks for whatever remains of the quantum before draining again.
+    fn _throttled_loop(&self, quantum: Duration) -> ! {
+        let mut next_wakeup = Instant::now() + quantum;
         loop {
-            while let Ok(task) = POOL.receiver.try_recv() {
-                trace!("dynamic thread: running task");
-                task.run();
+            let stealers = self.pool.stealers();
+            while let Some(task) = worker::find_task(&self.pool.injector, &stealers) {
+                trace!("{}: running task", worker::local_name());
+                worker::run_task(task);
+            }
+
+            let now = Instant::now();
+            if let Some(remaining) = next_wakeup.checked_duration_since(now) {
+                self.note_parking();
+                thread::park_timeout(remaining);
             }
-            trace!(
-                "dynamic thread: parking - {:?}",
-                std::thread::current().id()
-            );
-            parker();
+            next_wakeup = next_wakeup.max(now) + quantum;
         }
     }
     fn _standalone(&self) {
-        while let Ok(task) = POOL.receiver.try_recv() {
-            task.run();
+        self.install_worker();
+        let stealers = self.pool.stealers();
+        while let Some(task) = worker::find_task(&self.pool.injector, &stealers) {
+            worker::run_task(task);
         }
-        trace!("standalone thread: quitting.");
+        if let Some(metrics) = worker::local_metrics() {
+            self.pool.deregister_worker(&metrics);
+        }
+        trace!("{}: quitting", worker::local_name());
     }
 }
-
-static DYNAMIC_POOL_MANAGER: OnceCell<DynamicPoolManager> = OnceCell::new();
-
-static POOL: Lazy<Pool> = Lazy::new(|| {
-    let runner = Arc::new(AsyncRunner {});
-
-    DYNAMIC_POOL_MANAGER
-        .set(DynamicPoolManager::new(*low_watermark() as usize, runner))
-        .expect("couldn't create dynamic pool manager");
-    DYNAMIC_POOL_MANAGER
-        .get()
-        .expect("couldn't get static pool manager")
-        .initialize();
-
-    let (sender, receiver) = unbounded();
-    Pool { sender, receiver }
-});
@@ -0,0 +1,234 @@
+//!
+//! Per-thread local run queue used by the lightweight process pool.
+//!
+//! Every thread spawned by [`crate::pool`] owns a LIFO local queue
+//! ([`crossbeam_deque::Worker`]) that it pushes newly scheduled tasks onto
+//! and drains from first. When a thread's local queue and the shared
+//! [`crossbeam_deque::Injector`] both run dry, it steals directly from
+//! another worker's [`crossbeam_deque::Stealer`].
+
+use crate::metrics::WorkerMetrics;
+use crossbeam_deque::{Injector, Steal, Stealer, Worker};
+use lightproc::lightproc::LightProc;
+use lightproc::proc_stack::ProcStack;
+use std::cell::RefCell;
+use std::sync::Arc;
+
+/// A live worker thread's stealer and metrics, kept together so a retiring
+/// or deregistering thread can remove both in one step without the two
+/// drifting out of sync.
+pub(crate) struct WorkerEntry {
+    pub(crate) stealer: Stealer<LightProc>,
+    pub(crate) metrics: Arc<WorkerMetrics>,
+}
+
+thread_local! {
+    /// This thread's local run queue, installed by [`set_local_queue`].
+    /// `None` on any thread that isn't a pool worker (e.g. a thread that
+    /// merely calls [`crate::pool::spawn`] from the outside).
+    static LOCAL_QUEUE: RefCell<Option<Worker<LightProc>>> = RefCell::new(None);
+
+    /// The [`ProcStack`] of the [`LightProc`] currently running on this
+    /// thread, if any.
+    static PROC_STACK: RefCell<Option<ProcStack>> = RefCell::new(None);
+
+    /// This thread's metrics, installed by [`set_local_metrics`]. `None` on
+    /// any thread that isn't a pool worker.
+    static METRICS: RefCell<Option<Arc<WorkerMetrics>>> = RefCell::new(None);
+
+    /// This thread's display name, installed by [`set_local_name`]. `None`
+    /// on any thread that isn't a pool worker.
+    static NAME: RefCell<Option<String>> = RefCell::new(None);
+}
+
+/// Installs `queue` as the calling thread's local run queue.
+///
+/// Must be called once, before the first task runs, by every thread a pool
+/// spawns.
+pub(crate) fn set_local_queue(queue: Worker<LightProc>) {
+    LOCAL_QUEUE.with(|local| *local.borrow_mut() = Some(queue));
+}
+
+/// Installs `metrics` as the calling thread's metrics counters.
+///
+/// Must be called once, before the first task runs, by every thread a pool
+/// spawns.
+pub(crate) fn set_local_metrics(metrics: Arc<WorkerMetrics>) {
+    METRICS.with(|m| *m.borrow_mut() = Some(metrics));
+}
+
+/// Runs `f` with the calling thread's metrics, if it has any installed.
+pub(crate) fn with_local_metrics(f: impl FnOnce(&WorkerMetrics)) {
+    METRICS.with(|m| {
+        if let Some(metrics) = m.borrow().as_ref() {
+            f(metrics);
+        }
+    });
+}
+
+/// The calling thread's installed metrics handle, if any, for pools that
+/// need to identify this thread's entry when it retires.
+pub(crate) fn local_metrics() -> Option<Arc<WorkerMetrics>> {
+    METRICS.with(|m| m.borrow().clone())
+}
+
+/// The current length of the calling thread's local queue, if it has one.
+pub(crate) fn local_queue_len() -> Option<usize> {
+    LOCAL_QUEUE.with(|local| local.borrow().as_ref().map(Worker::len))
+}
+
+/// Installs `name` as the calling thread's display name, for logging.
+///
+/// Must be called once, before the first task runs, by every thread a pool
+/// spawns.
+pub(crate) fn set_local_name(name: String) {
+    NAME.with(|n| *n.borrow_mut() = Some(name));
+}
+
+/// The calling thread's installed display name, if any, or `"worker"` on a
+/// thread with none installed.
+pub(crate) fn local_name() -> String {
+    NAME.with(|n| n.borrow().clone())
+        .unwrap_or_else(|| "worker".to_owned())
+}
+
+/// Sets the [`ProcStack`] of the task about to run on this thread.
+fn set_proc_stack(stack: Option<ProcStack>) {
+    PROC_STACK.with(|s| *s.borrow_mut() = stack);
+}
+
+/// Runs `f` with the [`ProcStack`] of the task currently executing on this
+/// thread, if any.
+pub fn get_proc_stack<F, R>(f: F) -> Option<R>
+where
+    F: FnOnce(&ProcStack) -> R,
+{
+    PROC_STACK.with(|s| s.borrow().as_ref().map(f))
+}
+
+/// Runs `task`, installing its [`ProcStack`] on this thread's thread-local
+/// for the duration so [`get_proc_stack`] reflects the task actually
+/// executing (e.g. for `_parent_id` bookkeeping in [`crate::pool::AsyncPool::spawn`]).
+pub(crate) fn run_task(task: LightProc) {
+    set_proc_stack(Some(task.stack().clone()));
+    task.run();
+    set_proc_stack(None);
+}
+
+/// Schedules `task` onto the calling thread's local queue, or `injector` if
+/// the calling thread isn't a pool worker.
+pub(crate) fn push_local_or(task: LightProc, injector: &Injector<LightProc>) {
+    let task = LOCAL_QUEUE.with(|local| match local.borrow().as_ref() {
+        Some(queue) => {
+            queue.push(task);
+            None
+        }
+        None => Some(task),
+    });
+
+    if let Some(task) = task {
+        injector.push(task);
+    }
+}
+
+/// Finds one runnable task for the calling thread: first from its own local
+/// queue, then by stealing a batch from `injector`, then by stealing
+/// directly from one of `stealers`.
+///
+/// Returns `None` only once all three report [`Steal::Empty`].
+///
+/// # Panics
+///
+/// Panics if the calling thread has no local queue installed (i.e.
+/// [`set_local_queue`] was never called on it).
+pub(crate) fn find_task(
+    injector: &Injector<LightProc>,
+    stealers: &[Stealer<LightProc>],
+) -> Option<LightProc> {
+    let task = LOCAL_QUEUE.with(|local| {
+        let local = local.borrow();
+        let local = local
+            .as_ref()
+            .expect("worker: find_task called on a thread with no local queue");
+
+        if let Some(task) = local.pop() {
+            return Some(task);
+        }
+
+        with_local_metrics(WorkerMetrics::record_steal_attempt);
+        std::iter::repeat_with(|| {
+            injector
+                .steal_batch_and_pop(local)
+                .or_else(|| stealers.iter().map(Stealer::steal).collect())
+        })
+        .find(|s| !s.is_retry())
+        .and_then(Steal::success)
+        .map(|task| {
+            with_local_metrics(WorkerMetrics::record_steal_success);
+            task
+        })
+    });
+
+    if task.is_some() {
+        with_local_metrics(WorkerMetrics::record_task_polled);
+    }
+
+    task
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dummy_task() -> LightProc {
+        let (task, _handle) = LightProc::recoverable(async {}, |_t| {}, ProcStack::default());
+        task
+    }
+
+    #[test]
+    fn push_local_or_routes_to_injector_off_worker() {
+        // No local queue installed on this thread (a test thread never runs
+        // `set_local_queue`), so the task must land on the injector instead.
+        let injector = Injector::new();
+        push_local_or(dummy_task(), &injector);
+
+        match injector.steal() {
+            Steal::Success(_) => {}
+            other => panic!("expected the task on the injector, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn push_local_or_prefers_local_queue_on_worker() {
+        let local = Worker::new_lifo();
+        set_local_queue(local);
+
+        let injector = Injector::new();
+        push_local_or(dummy_task(), &injector);
+
+        assert_eq!(local_queue_len(), Some(1));
+        assert!(matches!(injector.steal(), Steal::Empty));
+    }
+
+    #[test]
+    fn find_task_records_steal_attempts_and_successes() {
+        // This thread's own local queue and the injector are both empty, so
+        // `find_task` must fall through to stealing from `other`'s queue.
+        set_local_queue(Worker::new_lifo());
+        let metrics = Arc::new(WorkerMetrics::default());
+        set_local_metrics(metrics.clone());
+
+        let other = Worker::new_lifo();
+        other.push(dummy_task());
+        let stealers = [other.stealer()];
+
+        let injector = Injector::new();
+        let task = find_task(&injector, &stealers);
+
+        assert!(task.is_some());
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.steals_attempted, 1);
+        assert_eq!(snapshot.steals_succeeded, 1);
+        assert_eq!(snapshot.tasks_polled, 1);
+    }
+}